@@ -1,20 +1,31 @@
 //! S3 implementation of storage backend
 
+use std::collections::{BTreeSet, HashMap};
 use std::fmt;
+use std::ops::Range;
 use std::path::Path;
 use std::pin::Pin;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::task::Poll;
+use std::time::Duration;
 
 use aws_config::SdkConfig;
 use aws_sdk_s3::operation::create_bucket::CreateBucketError;
 use aws_sdk_s3::primitives::{ByteStream, SdkBody};
-use aws_sdk_s3::types::CreateBucketConfiguration;
+use aws_sdk_s3::types::{
+    CompletedMultipartUpload, CompletedPart, CreateBucketConfiguration, Delete, ObjectIdentifier,
+};
 use aws_sdk_s3::Client;
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::result::SdkError;
+use aws_smithy_types::error::metadata::ProvideErrorMetadata;
 use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use futures::stream::{FuturesUnordered, TryStreamExt};
 use http_body::{Frame, SizeHint};
 use libsql_sys::name::NamespaceName;
+use rand::Rng;
 use tokio_util::sync::ReusableBoxFuture;
 
 use super::{Backend, SegmentMeta};
@@ -22,10 +33,157 @@ use crate::io::compat::copy_to_file;
 use crate::io::{FileExt, Io, StdIO};
 use crate::storage::{Error, Result};
 
+/// Segments smaller than this are uploaded with a single `put_object` call. Bigger segments are
+/// split into parts and uploaded with the S3 multipart API so we don't run into S3's 5 GiB
+/// single-PUT limit and so the upload can be parallelized.
+const DEFAULT_MULTIPART_THRESHOLD: u64 = 16 * 1024 * 1024;
+/// S3 requires every part but the last to be at least 5 MiB.
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+/// Default size of each part of a multipart upload.
+const DEFAULT_PART_SIZE: u64 = 8 * 1024 * 1024;
+/// Default number of parts uploaded concurrently.
+const DEFAULT_MAX_CONCURRENT_UPLOADS: usize = 8;
+
+/// How aggressively to retry a failed S3 request.
+#[derive(Debug, Clone, Copy)]
+pub enum RetryMode {
+    /// Exponential backoff with full jitter, driven solely by the error returned.
+    Standard,
+    /// Like `Standard`, but every backoff delay is doubled (still capped at `max_delay`). The
+    /// doubling applies to every retryable error alike; throttling is not tracked separately.
+    Adaptive,
+}
+
+/// Retry/backoff policy applied to every S3 request issued by [`S3Backend`].
+#[derive(Debug, Clone, Copy)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+    pub mode: RetryMode,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(20),
+            jitter: true,
+            mode: RetryMode::Standard,
+        }
+    }
+}
+
+impl RetryConfig {
+    fn backoff_delay(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(1u32 << attempt.min(20));
+        let mut delay = exp.min(self.max_delay);
+        if matches!(self.mode, RetryMode::Adaptive) {
+            // Back off twice as hard as `Standard`, whatever the error was.
+            delay = (delay * 2).min(self.max_delay);
+        }
+        if self.jitter {
+            let millis = delay.as_millis() as u64;
+            let jittered = if millis == 0 {
+                0
+            } else {
+                rand::thread_rng().gen_range(0..=millis)
+            };
+            Duration::from_millis(jittered)
+        } else {
+            delay
+        }
+    }
+}
+
+/// Returns true if an S3 error is worth retrying: throttling, 5xx, or a connection/timeout
+/// failure. 4xx errors like `NoSuchKey`/`AccessDenied` are not retried.
+fn is_retryable<E: ProvideErrorMetadata>(err: &SdkError<E, HttpResponse>) -> bool {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            true
+        }
+        SdkError::ServiceError(service_err) => {
+            let status = service_err.raw().status().as_u16();
+            if status == 503 || (500..600).contains(&status) {
+                return true;
+            }
+            matches!(
+                service_err.err().code(),
+                Some("SlowDown") | Some("RequestTimeout") | Some("ThrottlingException")
+            )
+        }
+        _ => false,
+    }
+}
+
+/// Runs `op` up to `retry.max_attempts` times, retrying retryable errors with exponential
+/// backoff and full jitter. Fails fast on non-retryable errors, and returns a typed
+/// [`Error::unhandled`] once attempts are exhausted instead of panicking.
+async fn with_retry<T, E, F, Fut>(retry: &RetryConfig, mut op: F) -> Result<T>
+where
+    E: ProvideErrorMetadata + std::fmt::Debug,
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = std::result::Result<T, SdkError<E, HttpResponse>>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Ok(v) => return Ok(v),
+            Err(e) => {
+                attempt += 1;
+                if attempt >= retry.max_attempts || !is_retryable(&e) {
+                    return Err(Error::unhandled(e, "s3 request failed"));
+                }
+                tokio::time::sleep(retry.backoff_delay(attempt)).await;
+            }
+        }
+    }
+}
+
 pub struct S3Backend<IO> {
     client: Client,
     default_config: Arc<S3Config>,
     io: IO,
+    /// Per-(bucket, prefix, namespace) cache of known segments, so most lookups can be served
+    /// without a `list_objects_v2` round-trip.
+    segments: RwLock<HashMap<RegistryKey, NamespaceState>>,
+}
+
+/// Identifies a namespace's segment registry cache entry. A bare `NamespaceName` is not enough:
+/// two configs that differ in `bucket` or `prefix` address disjoint keyspaces in S3 even for the
+/// same namespace name, so keying on the name alone would bleed segments between tenants or
+/// deployments that happen to share an `S3Backend` instance.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RegistryKey {
+    bucket: String,
+    prefix: String,
+    namespace: NamespaceName,
+}
+
+impl RegistryKey {
+    fn new(config: &S3Config, namespace: &NamespaceName) -> Self {
+        Self {
+            bucket: config.bucket.clone(),
+            prefix: config.normalized_prefix(),
+            namespace: namespace.clone(),
+        }
+    }
+}
+
+/// Cached state for a single namespace's segments.
+#[derive(Default)]
+struct NamespaceState {
+    /// All segments we currently know about, ordered the same way S3 keys are: biggest
+    /// `start_frame_no` first.
+    segments: BTreeSet<SegmentKey>,
+    /// True once a scan starting from the very top of the keyspace has reconnected with the
+    /// segment we already believed was newest (or found the namespace empty), confirming no
+    /// segment newer than anything in `segments` is hiding from us. Only then can a cache hit be
+    /// trusted without talking to S3.
+    tip_is_current: bool,
 }
 
 impl S3Backend<StdIO> {
@@ -51,6 +209,11 @@ impl<IO: Io> S3Backend<IO> {
             bucket,
             cluster_id,
             aws_config,
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            max_concurrent_uploads: DEFAULT_MAX_CONCURRENT_UPLOADS,
+            retry: RetryConfig::default(),
+            prefix: None,
         };
 
         let bucket_config = CreateBucketConfiguration::builder()
@@ -88,6 +251,7 @@ impl<IO: Io> S3Backend<IO> {
             client,
             default_config: config.into(),
             io,
+            segments: RwLock::new(HashMap::new()),
         })
     }
 
@@ -109,15 +273,60 @@ impl<IO: Io> S3Backend<IO> {
     }
 
     async fn s3_get(&self, config: &S3Config, key: String) -> Result<ByteStream> {
-        Ok(self
-            .client
-            .get_object()
-            .bucket(&config.bucket)
-            .key(key)
-            .send()
-            .await
-            .unwrap()
-            .body)
+        let resp = with_retry(&config.retry, || {
+            self.client
+                .get_object()
+                .bucket(&config.bucket)
+                .key(&key)
+                .send()
+        })
+        .await?;
+        Ok(resp.body)
+    }
+
+    /// Like [`Self::s3_get`], but only fetches the given byte range of the object.
+    async fn s3_get_range(
+        &self,
+        config: &S3Config,
+        key: String,
+        range: Range<u64>,
+    ) -> Result<ByteStream> {
+        let http_range = format_http_range(range);
+        let resp = with_retry(&config.retry, || {
+            self.client
+                .get_object()
+                .bucket(&config.bucket)
+                .key(&key)
+                .range(&http_range)
+                .send()
+        })
+        .await?;
+        Ok(resp.body)
+    }
+
+    /// Fetches the byte range of `segment_key`'s data object covering `frames`, as resolved
+    /// through the segment's index, and writes it to `dest_path`.
+    async fn fetch_segment_data_range(
+        &self,
+        config: &S3Config,
+        folder_key: &FolderKey<'_>,
+        segment_key: &SegmentKey,
+        frames: Range<u64>,
+        dest_path: &Path,
+    ) -> Result<()> {
+        let index = self
+            .fetch_segment_index(config, folder_key, segment_key)
+            .await?;
+        let byte_range = segment_frame_byte_range(segment_key, &index, frames.clone())
+            .ok_or(Error::FrameNotFound(frames.start))?;
+
+        let key = s3_segment_data_key(folder_key, segment_key);
+        let stream = self.s3_get_range(config, key, byte_range).await?;
+        let reader = stream.into_async_read();
+        let file = self.io.open(false, false, true, dest_path)?;
+        copy_to_file(reader, file).await?;
+
+        Ok(())
     }
 
     async fn fetch_segment_index(
@@ -134,45 +343,523 @@ impl<IO: Io> S3Backend<IO> {
         Ok(index)
     }
 
-    /// Find the most recent, and biggest segment that may contain `frame_no`
+    /// Find the most recent, and biggest segment that may contain `frame_no`. Served from the
+    /// in-memory registry when possible, only falling back to S3 when we can't yet be sure the
+    /// cache reflects the true tip of the keyspace.
     async fn find_segment(
         &self,
         config: &S3Config,
         folder_key: &FolderKey<'_>,
         frame_no: u64,
     ) -> Result<Option<SegmentKey>> {
-        let lookup_key = s3_segment_index_lookup_key(&folder_key, frame_no);
+        let key = RegistryKey::new(config, folder_key.namespace);
+
+        let cached = {
+            let registry = self.segments.read().unwrap();
+            registry.get(&key).map(|state| {
+                (
+                    floor_segment(&state.segments, frame_no),
+                    state.tip_is_current,
+                    state.segments.is_empty(),
+                )
+            })
+        };
 
-        let objects = self
-            .client
-            .list_objects_v2()
-            .bucket(&config.bucket)
-            .start_after(lookup_key)
-            .send()
+        // Only trust a hit once we know the cache has been reconciled with the true tip of the
+        // keyspace -- otherwise a segment newer than anything we've seen could be hiding from us,
+        // which matters even for an old `frame_no` since `floor_segment` may have missed a newer,
+        // bigger segment whose `start_frame_no` is still <= `frame_no`.
+        match cached {
+            Some((Some(segment_key), true, _)) => return Ok(Some(segment_key)),
+            // A scan from the top that found nothing, with the cache still empty, means this
+            // namespace has no segments at all: that's an authoritative answer, not a gap in what
+            // we've scanned so far, so there's no need to ever ask S3 about it again.
+            Some((None, true, true)) => return Ok(None),
+            _ => {}
+        }
+
+        self.refresh_segment_registry(config, folder_key, frame_no)
+            .await?;
+
+        let registry = self.segments.read().unwrap();
+        Ok(registry
+            .get(&key)
+            .and_then(|state| floor_segment(&state.segments, frame_no)))
+    }
+
+    /// Brings the in-memory registry up to date enough to answer a lookup for `frame_no`.
+    ///
+    /// First reconnects with the top of the keyspace: pages from the very beginning of the
+    /// namespace's indexes -- newest segment first, since that's how the reversed `SegmentKey`
+    /// encoding sorts -- until it finds the segment already believed to be newest, or runs out of
+    /// objects entirely. This is what lets us mark the cache as reflecting the true tip, and is
+    /// cheap when nothing changed: it stops as soon as it reconnects with a segment we already
+    /// know about. If the cache still can't answer the query afterwards (`frame_no` is older than
+    /// anything scanned so far), it continues paginating from where the cache currently ends,
+    /// extending further into the past.
+    async fn refresh_segment_registry(
+        &self,
+        config: &S3Config,
+        folder_key: &FolderKey<'_>,
+        frame_no: u64,
+    ) -> Result<()> {
+        let key = RegistryKey::new(config, folder_key.namespace);
+        let index_prefix = format!("{folder_key}/indexes/");
+
+        let newest_known = {
+            let registry = self.segments.read().unwrap();
+            registry
+                .get(&key)
+                .and_then(|s| s.segments.iter().next().copied())
+        };
+
+        let mut discovered = Vec::new();
+        let mut continuation_token = None;
+        'top_scan: loop {
+            let resp = with_retry(&config.retry, || {
+                let mut req = self
+                    .client
+                    .list_objects_v2()
+                    .bucket(&config.bucket)
+                    .prefix(&index_prefix);
+                if let Some(token) = &continuation_token {
+                    req = req.continuation_token(token);
+                }
+                req.send()
+            })
+            .await?;
+
+            for obj in resp.contents() {
+                let Some(segment_key) = parse_segment_key(obj.key()) else {
+                    continue;
+                };
+                if Some(segment_key) == newest_known {
+                    break 'top_scan;
+                }
+                discovered.push(segment_key);
+            }
+
+            if resp.is_truncated().unwrap_or(false) {
+                continuation_token = resp.next_continuation_token().map(str::to_string);
+                if continuation_token.is_none() {
+                    break;
+                }
+            } else {
+                break;
+            }
+        }
+
+        {
+            let mut registry = self.segments.write().unwrap();
+            let state = registry.entry(key.clone()).or_default();
+            state.segments.extend(discovered);
+            state.tip_is_current = true;
+        }
+
+        let already_covers = {
+            let registry = self.segments.read().unwrap();
+            registry
+                .get(&key)
+                .is_some_and(|s| floor_segment(&s.segments, frame_no).is_some())
+        };
+        if already_covers {
+            return Ok(());
+        }
+
+        let oldest_known = {
+            let registry = self.segments.read().unwrap();
+            registry
+                .get(&key)
+                .and_then(|s| s.segments.iter().next_back().copied())
+        };
+        let start_after = oldest_known
+            .map(|k| s3_segment_index_key(folder_key, &k))
+            .unwrap_or_else(|| s3_segment_index_lookup_key(folder_key, frame_no));
+
+        let mut continuation_token = None;
+        loop {
+            let resp = with_retry(&config.retry, || {
+                let mut req = self
+                    .client
+                    .list_objects_v2()
+                    .bucket(&config.bucket)
+                    .prefix(&index_prefix)
+                    .start_after(&start_after);
+                if let Some(token) = &continuation_token {
+                    req = req.continuation_token(token);
+                }
+                req.send()
+            })
+            .await?;
+
+            let mut found = false;
+            {
+                let mut registry = self.segments.write().unwrap();
+                let state = registry.entry(key.clone()).or_default();
+                for obj in resp.contents() {
+                    let Some(segment_key) = parse_segment_key(obj.key()) else {
+                        continue;
+                    };
+                    state.segments.insert(segment_key);
+                    if segment_key.start_frame_no <= frame_no {
+                        found = true;
+                    }
+                }
+            }
+
+            if found {
+                break;
+            }
+            if resp.is_truncated().unwrap_or(false) {
+                continuation_token = resp.next_continuation_token().map(str::to_string);
+                if continuation_token.is_none() {
+                    break;
+                }
+            } else {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Paginates `list_objects_v2` over both the `segments/` and `indexes/` prefixes of
+    /// `folder_key`, and returns every segment whose `end_frame_no <= frame_no`, i.e. every
+    /// segment entirely behind the retention boundary.
+    async fn list_stale_segments(
+        &self,
+        config: &S3Config,
+        folder_key: &FolderKey<'_>,
+        frame_no: u64,
+    ) -> Result<Vec<SegmentKey>> {
+        let mut keys = BTreeSet::new();
+
+        for subdir in ["segments", "indexes"] {
+            let prefix = format!("{folder_key}/{subdir}/");
+            let mut continuation_token = None;
+
+            loop {
+                let resp = with_retry(&config.retry, || {
+                    let mut req = self
+                        .client
+                        .list_objects_v2()
+                        .bucket(&config.bucket)
+                        .prefix(&prefix);
+                    if let Some(token) = &continuation_token {
+                        req = req.continuation_token(token);
+                    }
+                    req.send()
+                })
+                .await?;
+
+                for obj in resp.contents() {
+                    if let Some(segment_key) = parse_segment_key(obj.key()) {
+                        keys.insert(segment_key);
+                    }
+                }
+
+                if resp.is_truncated().unwrap_or(false) {
+                    continuation_token = resp.next_continuation_token().map(str::to_string);
+                    if continuation_token.is_none() {
+                        break;
+                    }
+                } else {
+                    break;
+                }
+            }
+        }
+
+        // `end_frame_no <= frame_no` alone is the retention boundary guard: it's exactly the
+        // segments entirely behind `frame_no`, so it already excludes any segment that still
+        // `includes` it (a segment can only `include` `frame_no` if its `end_frame_no` is
+        // strictly greater).
+        Ok(keys
+            .into_iter()
+            .filter(|key| key.end_frame_no <= frame_no)
+            .collect())
+    }
+
+    /// Deletes the given keys in batches of up to 1000, the maximum `delete_objects` accepts in a
+    /// single request.
+    async fn delete_keys(&self, config: &S3Config, keys: Vec<String>) -> Result<()> {
+        for chunk in keys.chunks(1000) {
+            let objects = chunk
+                .iter()
+                .map(|key| ObjectIdentifier::builder().key(key).build().unwrap())
+                .collect::<Vec<_>>();
+            let delete = Delete::builder()
+                .set_objects(Some(objects))
+                .build()
+                .unwrap();
+
+            with_retry(&config.retry, || {
+                self.client
+                    .delete_objects()
+                    .bucket(&config.bucket)
+                    .delete(delete.clone())
+                    .send()
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Lists the segments of `namespace`, applying `filter`'s predicates, by paginating
+    /// `list_objects_v2` over the `segments/` prefix and parsing each key back into a
+    /// [`SegmentKey`]. When `filter.summarize` is set, returns aggregate stats instead of every
+    /// matching entry.
+    pub async fn list_segments(
+        &self,
+        config: &S3Config,
+        namespace: &NamespaceName,
+        filter: &SegmentFilter,
+    ) -> Result<SegmentListing> {
+        let prefix = config.normalized_prefix();
+        let folder_key = FolderKey {
+            prefix: &prefix,
+            cluster_id: &config.cluster_id,
+            namespace,
+        };
+        let segments_prefix = format!("{folder_key}/segments/");
+
+        let mut entries = Vec::new();
+        let mut stats = SegmentStats::default();
+        let mut continuation_token = None;
+
+        loop {
+            let resp = with_retry(&config.retry, || {
+                let mut req = self
+                    .client
+                    .list_objects_v2()
+                    .bucket(&config.bucket)
+                    .prefix(&segments_prefix);
+                if let Some(token) = &continuation_token {
+                    req = req.continuation_token(token);
+                }
+                req.send()
+            })
+            .await?;
+
+            for obj in resp.contents() {
+                let Some(segment_key) = parse_segment_key(obj.key()) else {
+                    continue;
+                };
+                let size = obj.size().unwrap_or(0).max(0) as u64;
+                let created_at = obj
+                    .last_modified()
+                    .and_then(|t| DateTime::from_timestamp(t.secs(), t.subsec_nanos()))
+                    .unwrap_or_else(Utc::now);
+
+                if !filter.matches(&segment_key, size, created_at) {
+                    continue;
+                }
+
+                if filter.summarize {
+                    stats.count += 1;
+                    stats.total_bytes += size;
+                } else {
+                    entries.push(SegmentEntry {
+                        segment_key,
+                        size,
+                        created_at,
+                    });
+                }
+            }
+
+            if resp.is_truncated().unwrap_or(false) {
+                continuation_token = resp.next_continuation_token().map(str::to_string);
+                if continuation_token.is_none() {
+                    break;
+                }
+            } else {
+                break;
+            }
+        }
+
+        Ok(if filter.summarize {
+            SegmentListing::Summary(stats)
+        } else {
+            SegmentListing::Entries(entries)
+        })
+    }
+
+    /// Upload `segment_data` as a multipart upload, splitting it into `config.part_size` chunks
+    /// and uploading up to `config.max_concurrent_uploads` parts concurrently. On any part
+    /// failure, the multipart upload is aborted so no orphaned parts are left behind in the
+    /// bucket.
+    async fn store_multipart(
+        &self,
+        config: &S3Config,
+        key: String,
+        segment_data: Arc<impl FileExt>,
+        total_len: u64,
+    ) -> Result<()> {
+        let part_size = config.part_size.max(MIN_PART_SIZE);
+        let num_parts = total_len.div_ceil(part_size);
+
+        let create_resp = with_retry(&config.retry, || {
+            self.client
+                .create_multipart_upload()
+                .bucket(&config.bucket)
+                .key(&key)
+                .send()
+        })
+        .await?;
+        let upload_id = create_resp.upload_id().unwrap().to_string();
+
+        match self
+            .upload_parts(
+                config,
+                &key,
+                &upload_id,
+                segment_data,
+                total_len,
+                part_size,
+                num_parts,
+            )
             .await
-            .unwrap();
+        {
+            Ok(mut parts) => {
+                parts.sort_by_key(|p| p.part_number());
+                with_retry(&config.retry, || {
+                    let completed = CompletedMultipartUpload::builder()
+                        .set_parts(Some(parts.clone()))
+                        .build();
+                    self.client
+                        .complete_multipart_upload()
+                        .bucket(&config.bucket)
+                        .key(&key)
+                        .upload_id(&upload_id)
+                        .multipart_upload(completed)
+                        .send()
+                })
+                .await?;
+
+                Ok(())
+            }
+            Err(e) => {
+                let _ = with_retry(&config.retry, || {
+                    self.client
+                        .abort_multipart_upload()
+                        .bucket(&config.bucket)
+                        .key(&key)
+                        .upload_id(&upload_id)
+                        .send()
+                })
+                .await;
+
+                Err(e)
+            }
+        }
+    }
 
-        let Some(contents) = objects.contents().first() else {
-            return Ok(None);
+    async fn upload_parts(
+        &self,
+        config: &S3Config,
+        key: &str,
+        upload_id: &str,
+        segment_data: Arc<impl FileExt>,
+        total_len: u64,
+        part_size: u64,
+        num_parts: u64,
+    ) -> Result<Vec<CompletedPart>> {
+        let mut futs = FuturesUnordered::new();
+        let mut parts = Vec::with_capacity(num_parts as usize);
+        let mut next_part = 0u64;
+
+        let spawn_part = |part_index: u64| {
+            let segment_data = segment_data.clone();
+            let offset = part_index * part_size;
+            let len = part_size.min(total_len - offset);
+            async move {
+                let body = read_exact_at(&*segment_data, offset, len as usize).await?;
+                let part_number = (part_index + 1) as i32;
+                let resp = with_retry(&config.retry, || {
+                    self.client
+                        .upload_part()
+                        .bucket(&config.bucket)
+                        .key(key)
+                        .upload_id(upload_id)
+                        .part_number(part_number)
+                        .body(ByteStream::from(body.clone()))
+                        .send()
+                })
+                .await?;
+                let e_tag = resp.e_tag().unwrap_or_default().to_string();
+                Result::<CompletedPart>::Ok(
+                    CompletedPart::builder()
+                        .part_number(part_number)
+                        .e_tag(e_tag)
+                        .build(),
+                )
+            }
         };
-        let key = contents.key().unwrap();
-        let key_path: &Path = key.as_ref();
-        let segment_key: SegmentKey = key_path
-            .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .parse()
-            .unwrap();
 
-        Ok(Some(segment_key))
+        for part_index in 0..num_parts.min(config.max_concurrent_uploads as u64) {
+            futs.push(spawn_part(part_index));
+            next_part += 1;
+        }
+
+        while let Some(part) = futs.try_next().await? {
+            parts.push(part);
+            if next_part < num_parts {
+                futs.push(spawn_part(next_part));
+                next_part += 1;
+            }
+        }
+
+        Ok(parts)
+    }
+}
+
+/// Reads exactly `len` bytes at `offset` from `file`, looping over short reads.
+async fn read_exact_at(file: &impl FileExt, offset: u64, len: usize) -> std::io::Result<Bytes> {
+    let mut out = BytesMut::with_capacity(len);
+    while out.len() < len {
+        let chunk = BytesMut::with_capacity(len - out.len());
+        let (chunk, ret) = file.read_at_async(chunk, offset + out.len() as u64).await;
+        ret?;
+        if chunk.is_empty() {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        out.extend_from_slice(&chunk);
     }
+    Ok(out.freeze())
 }
 
 pub struct S3Config {
     bucket: String,
     aws_config: SdkConfig,
     cluster_id: String,
+    /// Segments bigger than this many bytes are uploaded via multipart upload instead of a
+    /// single `put_object` call.
+    multipart_threshold: u64,
+    /// Size of each part in a multipart upload. Clamped to S3's 5 MiB minimum.
+    part_size: u64,
+    /// Maximum number of parts uploaded concurrently during a multipart upload.
+    max_concurrent_uploads: usize,
+    /// Retry/backoff policy applied to every request issued against this config.
+    retry: RetryConfig,
+    /// Optional key prefix prepended to every generated key, so multiple independent libsql
+    /// deployments can safely share a single bucket.
+    prefix: Option<String>,
+}
+
+impl S3Config {
+    /// Returns the configured prefix normalized to either be empty, or end in a single `/`.
+    fn normalized_prefix(&self) -> String {
+        match self.prefix.as_deref() {
+            Some(p) if !p.is_empty() => {
+                if p.ends_with('/') {
+                    p.to_string()
+                } else {
+                    format!("{p}/")
+                }
+            }
+            _ => String::new(),
+        }
+    }
 }
 
 /// SegmentKey is used to index segment data, where keys a lexicographically ordered.
@@ -201,7 +888,7 @@ pub struct S3Config {
 /// dbg!(map.range(format!("{:019}", u64::MAX - 101)..).next());
 /// dbg!(map.range(format!("{:019}", u64::MAX - 5000)..).next());
 /// ```
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct SegmentKey {
     start_frame_no: u64,
     end_frame_no: u64,
@@ -213,6 +900,95 @@ impl SegmentKey {
     }
 }
 
+/// Ordered the same way the S3 key encoding sorts: biggest `start_frame_no` first, and for equal
+/// `start_frame_no`, biggest `end_frame_no` first.
+impl Ord for SegmentKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (u64::MAX - self.start_frame_no, u64::MAX - self.end_frame_no).cmp(&(
+            u64::MAX - other.start_frame_no,
+            u64::MAX - other.end_frame_no,
+        ))
+    }
+}
+
+impl PartialOrd for SegmentKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Finds the most recent, biggest segment in `segments` that may contain `frame_no`, mirroring
+/// the prefix search `find_segment` performs against S3.
+fn floor_segment(segments: &BTreeSet<SegmentKey>, frame_no: u64) -> Option<SegmentKey> {
+    let sentinel = SegmentKey {
+        start_frame_no: frame_no,
+        end_frame_no: u64::MAX,
+    };
+    segments.range(sentinel..).next().copied()
+}
+
+/// Filter predicates for [`S3Backend::list_segments`].
+#[derive(Debug, Clone, Default)]
+pub struct SegmentFilter {
+    pub frame_no_range: Option<Range<u64>>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    /// When set, [`S3Backend::list_segments`] returns aggregate stats instead of every entry.
+    pub summarize: bool,
+}
+
+impl SegmentFilter {
+    fn matches(&self, segment_key: &SegmentKey, size: u64, created_at: DateTime<Utc>) -> bool {
+        if let Some(range) = &self.frame_no_range {
+            if segment_key.end_frame_no <= range.start || segment_key.start_frame_no >= range.end {
+                return false;
+            }
+        }
+        if self.min_size.is_some_and(|min| size < min) {
+            return false;
+        }
+        if self.max_size.is_some_and(|max| size > max) {
+            return false;
+        }
+        if self.created_after.is_some_and(|after| created_at < after) {
+            return false;
+        }
+        if self
+            .created_before
+            .is_some_and(|before| created_at > before)
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// A single segment discovered by [`S3Backend::list_segments`].
+#[derive(Debug, Clone)]
+pub struct SegmentEntry {
+    pub segment_key: SegmentKey,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Aggregate stats produced by [`S3Backend::list_segments`] when [`SegmentFilter::summarize`] is
+/// set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SegmentStats {
+    pub count: u64,
+    pub total_bytes: u64,
+}
+
+/// The result of [`S3Backend::list_segments`]: either every matching entry, or, when
+/// [`SegmentFilter::summarize`] is set, just the aggregate count and byte total.
+#[derive(Debug, Clone)]
+pub enum SegmentListing {
+    Entries(Vec<SegmentEntry>),
+    Summary(SegmentStats),
+}
+
 impl From<&SegmentMeta> for SegmentKey {
     fn from(value: &SegmentMeta) -> Self {
         Self {
@@ -249,13 +1025,20 @@ impl fmt::Display for SegmentKey {
 }
 
 struct FolderKey<'a> {
+    /// Normalized bucket prefix (empty, or ending in `/`) that isolates this deployment's keys
+    /// from other tenants sharing the same bucket.
+    prefix: &'a str,
     cluster_id: &'a str,
     namespace: &'a NamespaceName,
 }
 
 impl fmt::Display for FolderKey<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "ns-{}:{}-v2", self.cluster_id, self.namespace)
+        write!(
+            f,
+            "{}ns-{}:{}-v2",
+            self.prefix, self.cluster_id, self.namespace
+        )
     }
 }
 
@@ -275,6 +1058,47 @@ fn s3_folder_key(cluster_id: &str, ns: &NamespaceName) -> String {
     format!("ns-{}:{}-v2", cluster_id, ns)
 }
 
+/// Parses an S3 object key's file stem back into a [`SegmentKey`], as produced by
+/// [`s3_segment_data_key`] or [`s3_segment_index_key`].
+fn parse_segment_key(key: Option<&str>) -> Option<SegmentKey> {
+    let key_path: &Path = key?.as_ref();
+    key_path.file_stem()?.to_str()?.parse().ok()
+}
+
+/// Formats a byte range the way object_store's `format_http_range` does: an inclusive end, or an
+/// open-ended `bytes=start-` when the end is unknown.
+fn format_http_range(range: Range<u64>) -> String {
+    if range.end == u64::MAX {
+        format!("bytes={}-", range.start)
+    } else {
+        format!("bytes={}-{}", range.start, range.end.saturating_sub(1))
+    }
+}
+
+/// The key under which a frame's byte offset is stored in a segment index. Indexes are built
+/// per-segment and know nothing of the namespace's global frame numbering, so `frame_no` is made
+/// relative to `segment_key.start_frame_no` before being used as a key; `frame_no` here is always a
+/// global frame number, the same domain as `SegmentMeta::start_frame_no`/`end_frame_no`.
+fn index_key(segment_key: &SegmentKey, frame_no: u64) -> [u8; 8] {
+    (frame_no - segment_key.start_frame_no).to_be_bytes()
+}
+
+/// Resolves a range of frame numbers to the byte range of the segment data object that contains
+/// them, using the segment's frame -> byte offset index. Returns an open-ended range when the end
+/// of `frames` falls outside of the index, e.g. because it is the last frame of the segment.
+fn segment_frame_byte_range(
+    segment_key: &SegmentKey,
+    index: &fst::Map<Vec<u8>>,
+    frames: Range<u64>,
+) -> Option<Range<u64>> {
+    let start = index.get(index_key(segment_key, frames.start))?;
+    let end = index
+        .get(index_key(segment_key, frames.end))
+        .unwrap_or(u64::MAX);
+
+    Some(start..end)
+}
+
 impl<IO> Backend for S3Backend<IO>
 where
     IO: Io,
@@ -288,37 +1112,59 @@ where
         segment_data: impl FileExt,
         segment_index: Vec<u8>,
     ) -> Result<()> {
+        let prefix = config.normalized_prefix();
         let folder_key = FolderKey {
+            prefix: &prefix,
             cluster_id: &config.cluster_id,
             namespace: &meta.namespace,
         };
         let segment_key = SegmentKey::from(&meta);
         let s3_data_key = s3_segment_data_key(&folder_key, &segment_key);
 
-        let body = FileStreamBody::new(segment_data).into_byte_stream();
-
-        self.client
-            .put_object()
-            .bucket(&self.default_config.bucket)
-            .body(body)
-            .key(s3_data_key)
-            .send()
-            .await
-            .unwrap();
+        let segment_data = Arc::new(segment_data);
+        let segment_len = segment_data.len().unwrap_or(0);
+        if segment_len > config.multipart_threshold {
+            self.store_multipart(config, s3_data_key, segment_data, segment_len)
+                .await?;
+        } else {
+            with_retry(&config.retry, || {
+                let body = FileStreamBody::new_inner(segment_data.clone()).into_byte_stream();
+                self.client
+                    .put_object()
+                    .bucket(&config.bucket)
+                    .body(body)
+                    .key(&s3_data_key)
+                    .send()
+            })
+            .await?;
+        }
 
         let s3_index_key = s3_segment_index_key(&folder_key, &segment_key);
 
         // TODO: store meta about the index?
-        let body = ByteStream::from(segment_index);
-
-        self.client
-            .put_object()
-            .bucket(&self.default_config.bucket)
-            .body(body)
-            .key(s3_index_key)
-            .send()
-            .await
-            .unwrap();
+        with_retry(&config.retry, || {
+            let body = ByteStream::from(segment_index.clone());
+            self.client
+                .put_object()
+                .bucket(&config.bucket)
+                .body(body)
+                .key(&s3_index_key)
+                .send()
+        })
+        .await?;
+
+        // Keep our own cache warm so a subsequent lookup from this writer doesn't need to hit S3:
+        // we just wrote `segment_key` ourselves, so we know for certain it's the newest segment
+        // we're aware of, and can mark the cache's tip as current without waiting for a listing
+        // to confirm it.
+        {
+            let mut registry = self.segments.write().unwrap();
+            let state = registry
+                .entry(RegistryKey::new(config, &meta.namespace))
+                .or_default();
+            state.segments.insert(segment_key);
+            state.tip_is_current = true;
+        }
 
         Ok(())
     }
@@ -330,7 +1176,9 @@ where
         frame_no: u64,
         dest_path: &Path,
     ) -> Result<fst::Map<Vec<u8>>> {
+        let prefix = config.normalized_prefix();
         let folder_key = FolderKey {
+            prefix: &prefix,
             cluster_id: &config.cluster_id,
             namespace: &namespace,
         };
@@ -350,9 +1198,48 @@ where
         }
     }
 
+    /// Fetches only the bytes of `frames` from the segment that contains them, instead of the
+    /// whole segment object, and writes them to `dest`.
+    async fn fetch_frames(
+        &self,
+        config: &Self::Config,
+        namespace: NamespaceName,
+        frames: Range<u64>,
+        dest: &Path,
+    ) -> Result<()> {
+        let prefix = config.normalized_prefix();
+        let folder_key = FolderKey {
+            prefix: &prefix,
+            cluster_id: &config.cluster_id,
+            namespace: &namespace,
+        };
+
+        let Some(segment_key) = self.find_segment(config, &folder_key, frames.start).await? else {
+            return Err(Error::FrameNotFound(frames.start));
+        };
+
+        if !segment_key.includes(frames.start) {
+            return Err(Error::FrameNotFound(frames.start));
+        }
+
+        // `segment_frame_byte_range` only knows how to resolve offsets within the segment its
+        // index belongs to: a `frames` range that extends past `segment_key.end_frame_no` would
+        // otherwise silently fall back to "read to the end of this segment" and hand the caller a
+        // truncated read instead of the frames it actually asked for. We don't support spanning
+        // multiple segments in a single call, so reject it instead.
+        if frames.end > segment_key.end_frame_no {
+            return Err(Error::FrameNotFound(frames.end));
+        }
+
+        self.fetch_segment_data_range(config, &folder_key, &segment_key, frames, dest)
+            .await
+    }
+
     async fn meta(&self, config: &Self::Config, namespace: NamespaceName) -> Result<super::DbMeta> {
         // request a key bigger than any other to get the last segment
+        let prefix = config.normalized_prefix();
         let folder_key = FolderKey {
+            prefix: &prefix,
             cluster_id: &config.cluster_id,
             namespace: &namespace,
         };
@@ -364,6 +1251,50 @@ where
         })
     }
 
+    /// Deletes every segment (data object and matching index object) whose `end_frame_no <=
+    /// frame_no`, so operators can reclaim space for WAL history that is no longer referenced.
+    async fn delete_segments_before(
+        &self,
+        config: &Self::Config,
+        namespace: NamespaceName,
+        frame_no: u64,
+    ) -> Result<()> {
+        let prefix = config.normalized_prefix();
+        let folder_key = FolderKey {
+            prefix: &prefix,
+            cluster_id: &config.cluster_id,
+            namespace: &namespace,
+        };
+
+        let stale = self
+            .list_stale_segments(config, &folder_key, frame_no)
+            .await?;
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        let keys = stale
+            .iter()
+            .flat_map(|segment_key| {
+                [
+                    s3_segment_data_key(&folder_key, segment_key),
+                    s3_segment_index_key(&folder_key, segment_key),
+                ]
+            })
+            .collect();
+        self.delete_keys(config, keys).await?;
+
+        let registry_key = RegistryKey::new(config, &namespace);
+        let mut registry = self.segments.write().unwrap();
+        if let Some(state) = registry.get_mut(&registry_key) {
+            for segment_key in &stale {
+                state.segments.remove(segment_key);
+            }
+        }
+
+        Ok(())
+    }
+
     fn default_config(&self) -> Arc<Self::Config> {
         self.default_config.clone()
     }
@@ -385,10 +1316,6 @@ struct FileStreamBody<F> {
 }
 
 impl<F> FileStreamBody<F> {
-    fn new(inner: F) -> Self {
-        Self::new_inner(inner.into())
-    }
-
     fn new_inner(inner: Arc<F>) -> Self {
         Self {
             inner,
@@ -523,6 +1450,11 @@ mod tests {
             bucket: "testbucket".into(),
             aws_config: aws_config.clone(),
             cluster_id: "123456789".into(),
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            max_concurrent_uploads: DEFAULT_MAX_CONCURRENT_UPLOADS,
+            retry: RetryConfig::default(),
+            prefix: None,
         };
 
         let storage = S3Backend::from_sdk_config_with_io(
@@ -609,4 +1541,626 @@ mod tests {
             .unwrap();
         assert_eq!(index.get(44u32.to_be_bytes()).unwrap(), 44);
     }
+
+    /// A namespace that has never had anything stored in it should still resolve cleanly, and
+    /// repeated lookups against it must keep agreeing with each other instead of flip-flopping
+    /// between "found nothing" and some stale cached answer.
+    #[tokio::test]
+    async fn s3_empty_namespace_lookups_are_stable() {
+        let _ = tracing_subscriber::fmt::try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let (aws_config, _s3) = setup(&dir);
+
+        let s3_config = S3Config {
+            bucket: "testbucket".into(),
+            aws_config: aws_config.clone(),
+            cluster_id: "123456789".into(),
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            max_concurrent_uploads: DEFAULT_MAX_CONCURRENT_UPLOADS,
+            retry: RetryConfig::default(),
+            prefix: None,
+        };
+
+        let storage = S3Backend::from_sdk_config_with_io(
+            aws_config,
+            "testbucket".into(),
+            "123456789".into(),
+            StdIO(()),
+        )
+        .await
+        .unwrap();
+
+        let ns = NamespaceName::from_string("empty-ns".into());
+
+        for _ in 0..2 {
+            let db_meta = storage.meta(&s3_config, ns.clone()).await.unwrap();
+            assert_eq!(db_meta.max_frame_no, 0);
+        }
+
+        let tmp = NamedTempFile::new().unwrap();
+        let err = storage
+            .fetch_segment(&s3_config, ns.clone(), 0, tmp.path())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::FrameNotFound(_)));
+    }
+
+    /// Two configs that differ only in `prefix` (i.e. two tenants sharing one bucket) must not
+    /// share segment registry state, even though they use the same `S3Backend` instance and the
+    /// same namespace name.
+    #[tokio::test]
+    async fn s3_tenant_prefix_isolation() {
+        let _ = tracing_subscriber::fmt::try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let (aws_config, _s3) = setup(&dir);
+
+        let storage = S3Backend::from_sdk_config_with_io(
+            aws_config.clone(),
+            "testbucket".into(),
+            "123456789".into(),
+            StdIO(()),
+        )
+        .await
+        .unwrap();
+
+        let tenant_a_config = S3Config {
+            bucket: "testbucket".into(),
+            aws_config: aws_config.clone(),
+            cluster_id: "123456789".into(),
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            max_concurrent_uploads: DEFAULT_MAX_CONCURRENT_UPLOADS,
+            retry: RetryConfig::default(),
+            prefix: Some("tenant-a".into()),
+        };
+        let tenant_b_config = S3Config {
+            bucket: "testbucket".into(),
+            aws_config: aws_config.clone(),
+            cluster_id: "123456789".into(),
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            max_concurrent_uploads: DEFAULT_MAX_CONCURRENT_UPLOADS,
+            retry: RetryConfig::default(),
+            prefix: Some("tenant-b".into()),
+        };
+
+        let f_path = dir.path().join("fs-segments");
+        std::fs::write(&f_path, vec![123; 8092]).unwrap();
+
+        // Same namespace name under both tenants -- only the prefix tells them apart.
+        let ns = NamespaceName::from_string("shared-ns".into());
+
+        let mut builder = MapBuilder::memory();
+        builder.insert(1u32.to_be_bytes(), 1).unwrap();
+        let index = builder.into_inner().unwrap();
+        storage
+            .store(
+                &tenant_a_config,
+                SegmentMeta {
+                    namespace: ns.clone(),
+                    segment_id: Uuid::new_v4(),
+                    start_frame_no: 0u64.into(),
+                    end_frame_no: 64u64.into(),
+                    created_at: Utc::now(),
+                },
+                std::fs::File::open(&f_path).unwrap(),
+                index,
+            )
+            .await
+            .unwrap();
+
+        let mut builder = MapBuilder::memory();
+        builder.insert(1u32.to_be_bytes(), 1).unwrap();
+        let index = builder.into_inner().unwrap();
+        storage
+            .store(
+                &tenant_b_config,
+                SegmentMeta {
+                    namespace: ns.clone(),
+                    segment_id: Uuid::new_v4(),
+                    start_frame_no: 0u64.into(),
+                    end_frame_no: 999u64.into(),
+                    created_at: Utc::now(),
+                },
+                std::fs::File::open(&f_path).unwrap(),
+                index,
+            )
+            .await
+            .unwrap();
+
+        let meta_a = storage.meta(&tenant_a_config, ns.clone()).await.unwrap();
+        assert_eq!(meta_a.max_frame_no, 64);
+
+        let meta_b = storage.meta(&tenant_b_config, ns.clone()).await.unwrap();
+        assert_eq!(meta_b.max_frame_no, 999);
+    }
+
+    /// `fetch_frames` against a segment whose `start_frame_no` is not zero must translate the
+    /// requested global frame numbers to offsets relative to that segment before looking them up
+    /// in its index.
+    #[tokio::test]
+    async fn s3_fetch_frames_relative_offset() {
+        let _ = tracing_subscriber::fmt::try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let (aws_config, _s3) = setup(&dir);
+
+        let s3_config = S3Config {
+            bucket: "testbucket".into(),
+            aws_config: aws_config.clone(),
+            cluster_id: "123456789".into(),
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            max_concurrent_uploads: DEFAULT_MAX_CONCURRENT_UPLOADS,
+            retry: RetryConfig::default(),
+            prefix: None,
+        };
+
+        let storage = S3Backend::from_sdk_config_with_io(
+            aws_config,
+            "testbucket".into(),
+            "123456789".into(),
+            StdIO(()),
+        )
+        .await
+        .unwrap();
+
+        let ns = NamespaceName::from_string("fetch-frames-ns".into());
+
+        // First segment: global frames 0..64.
+        let seg1_path = dir.path().join("fs-seg1");
+        std::fs::write(&seg1_path, vec![0u8; 128]).unwrap();
+        let mut builder = MapBuilder::memory();
+        builder.insert(0u64.to_be_bytes(), 0).unwrap();
+        let index1 = builder.into_inner().unwrap();
+        storage
+            .store(
+                &s3_config,
+                SegmentMeta {
+                    namespace: ns.clone(),
+                    segment_id: Uuid::new_v4(),
+                    start_frame_no: 0u64.into(),
+                    end_frame_no: 64u64.into(),
+                    created_at: Utc::now(),
+                },
+                std::fs::File::open(&seg1_path).unwrap(),
+                index1,
+            )
+            .await
+            .unwrap();
+
+        // Second segment: global frames 64..128. Its index is keyed relative to its own
+        // `start_frame_no` (64), so global frame 70 is stored under relative offset 6.
+        let seg2_data: Vec<u8> = (0u32..200).map(|b| (b % 251) as u8).collect();
+        let seg2_path = dir.path().join("fs-seg2");
+        std::fs::write(&seg2_path, &seg2_data).unwrap();
+        let mut builder = MapBuilder::memory();
+        builder.insert(6u64.to_be_bytes(), 50).unwrap();
+        builder.insert(16u64.to_be_bytes(), 150).unwrap();
+        let index2 = builder.into_inner().unwrap();
+        storage
+            .store(
+                &s3_config,
+                SegmentMeta {
+                    namespace: ns.clone(),
+                    segment_id: Uuid::new_v4(),
+                    start_frame_no: 64u64.into(),
+                    end_frame_no: 128u64.into(),
+                    created_at: Utc::now(),
+                },
+                std::fs::File::open(&seg2_path).unwrap(),
+                index2,
+            )
+            .await
+            .unwrap();
+
+        let tmp = NamedTempFile::new().unwrap();
+        storage
+            .fetch_frames(&s3_config, ns.clone(), 70..80, tmp.path())
+            .await
+            .unwrap();
+
+        let fetched = std::fs::read(tmp.path()).unwrap();
+        assert_eq!(fetched, &seg2_data[50..150]);
+    }
+
+    /// A `frames` range that extends past the end of the segment it starts in must be rejected
+    /// rather than silently truncated to that segment's tail.
+    #[tokio::test]
+    async fn s3_fetch_frames_rejects_cross_segment_range() {
+        let _ = tracing_subscriber::fmt::try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let (aws_config, _s3) = setup(&dir);
+
+        let s3_config = S3Config {
+            bucket: "testbucket".into(),
+            aws_config: aws_config.clone(),
+            cluster_id: "123456789".into(),
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            max_concurrent_uploads: DEFAULT_MAX_CONCURRENT_UPLOADS,
+            retry: RetryConfig::default(),
+            prefix: None,
+        };
+
+        let storage = S3Backend::from_sdk_config_with_io(
+            aws_config,
+            "testbucket".into(),
+            "123456789".into(),
+            StdIO(()),
+        )
+        .await
+        .unwrap();
+
+        let ns = NamespaceName::from_string("fetch-frames-span-ns".into());
+
+        for (start, end) in [(0u64, 64u64), (64, 128)] {
+            let seg_path = dir.path().join(format!("fs-seg-{start}"));
+            std::fs::write(&seg_path, vec![0u8; 64]).unwrap();
+            let mut builder = MapBuilder::memory();
+            builder.insert(0u64.to_be_bytes(), 0).unwrap();
+            let index = builder.into_inner().unwrap();
+            storage
+                .store(
+                    &s3_config,
+                    SegmentMeta {
+                        namespace: ns.clone(),
+                        segment_id: Uuid::new_v4(),
+                        start_frame_no: start.into(),
+                        end_frame_no: end.into(),
+                        created_at: Utc::now(),
+                    },
+                    std::fs::File::open(&seg_path).unwrap(),
+                    index,
+                )
+                .await
+                .unwrap();
+        }
+
+        let tmp = NamedTempFile::new().unwrap();
+        // Frame 60 is in the first segment (0..64), but 70 is in the second one: this range
+        // straddles the segment boundary and must be rejected, not silently truncated.
+        let err = storage
+            .fetch_frames(&s3_config, ns.clone(), 60..70, tmp.path())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::FrameNotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn s3_delete_segments_before() {
+        let _ = tracing_subscriber::fmt::try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let (aws_config, _s3) = setup(&dir);
+
+        let s3_config = S3Config {
+            bucket: "testbucket".into(),
+            aws_config: aws_config.clone(),
+            cluster_id: "123456789".into(),
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            max_concurrent_uploads: DEFAULT_MAX_CONCURRENT_UPLOADS,
+            retry: RetryConfig::default(),
+            prefix: None,
+        };
+
+        let storage = S3Backend::from_sdk_config_with_io(
+            aws_config,
+            "testbucket".into(),
+            "123456789".into(),
+            StdIO(()),
+        )
+        .await
+        .unwrap();
+
+        let f_path = dir.path().join("fs-segments");
+        std::fs::write(&f_path, vec![1u8; 64]).unwrap();
+        let ns = NamespaceName::from_string("gc-ns".into());
+
+        // Three segments: [0, 64), [64, 128), [128, 192). Retiring everything before frame 128
+        // should delete the first two and leave the third untouched.
+        for (start, end) in [(0u64, 64u64), (64, 128), (128, 192)] {
+            let mut builder = MapBuilder::memory();
+            builder.insert(0u64.to_be_bytes(), 0).unwrap();
+            let index = builder.into_inner().unwrap();
+            storage
+                .store(
+                    &s3_config,
+                    SegmentMeta {
+                        namespace: ns.clone(),
+                        segment_id: Uuid::new_v4(),
+                        start_frame_no: start.into(),
+                        end_frame_no: end.into(),
+                        created_at: Utc::now(),
+                    },
+                    std::fs::File::open(&f_path).unwrap(),
+                    index,
+                )
+                .await
+                .unwrap();
+        }
+
+        storage
+            .delete_segments_before(&s3_config, ns.clone(), 128)
+            .await
+            .unwrap();
+
+        let listing = storage
+            .list_segments(&s3_config, &ns, &SegmentFilter::default())
+            .await
+            .unwrap();
+        let SegmentListing::Entries(entries) = listing else {
+            panic!("expected entries, got a summary");
+        };
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].segment_key.start_frame_no, 128);
+        assert_eq!(entries[0].segment_key.end_frame_no, 192);
+
+        // The retained segment must still be fetchable.
+        let tmp = NamedTempFile::new().unwrap();
+        storage
+            .fetch_segment(&s3_config, ns.clone(), 150, tmp.path())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn s3_list_segments_filter_and_summarize() {
+        let _ = tracing_subscriber::fmt::try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let (aws_config, _s3) = setup(&dir);
+
+        let s3_config = S3Config {
+            bucket: "testbucket".into(),
+            aws_config: aws_config.clone(),
+            cluster_id: "123456789".into(),
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            max_concurrent_uploads: DEFAULT_MAX_CONCURRENT_UPLOADS,
+            retry: RetryConfig::default(),
+            prefix: None,
+        };
+
+        let storage = S3Backend::from_sdk_config_with_io(
+            aws_config,
+            "testbucket".into(),
+            "123456789".into(),
+            StdIO(()),
+        )
+        .await
+        .unwrap();
+
+        let ns = NamespaceName::from_string("list-segments-ns".into());
+
+        // Three segments of increasing size: [0, 64) small, [64, 128) small, [128, 192) big.
+        let sizes = [64usize, 64, 4096];
+        for (i, (start, end)) in [(0u64, 64u64), (64, 128), (128, 192)]
+            .into_iter()
+            .enumerate()
+        {
+            let f_path = dir.path().join(format!("fs-segment-{i}"));
+            std::fs::write(&f_path, vec![1u8; sizes[i]]).unwrap();
+            let mut builder = MapBuilder::memory();
+            builder.insert(0u64.to_be_bytes(), 0).unwrap();
+            let index = builder.into_inner().unwrap();
+            storage
+                .store(
+                    &s3_config,
+                    SegmentMeta {
+                        namespace: ns.clone(),
+                        segment_id: Uuid::new_v4(),
+                        start_frame_no: start.into(),
+                        end_frame_no: end.into(),
+                        created_at: Utc::now(),
+                    },
+                    std::fs::File::open(&f_path).unwrap(),
+                    index,
+                )
+                .await
+                .unwrap();
+        }
+
+        // Filtering by frame_no_range should only return the segment overlapping it.
+        let listing = storage
+            .list_segments(
+                &s3_config,
+                &ns,
+                &SegmentFilter {
+                    frame_no_range: Some(64..128),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let SegmentListing::Entries(entries) = listing else {
+            panic!("expected entries, got a summary");
+        };
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].segment_key.start_frame_no, 64);
+
+        // Filtering by min_size should only return the big segment.
+        let listing = storage
+            .list_segments(
+                &s3_config,
+                &ns,
+                &SegmentFilter {
+                    min_size: Some(1024),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let SegmentListing::Entries(entries) = listing else {
+            panic!("expected entries, got a summary");
+        };
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].segment_key.start_frame_no, 128);
+
+        // summarize should count all three segments and total their bytes.
+        let listing = storage
+            .list_segments(
+                &s3_config,
+                &ns,
+                &SegmentFilter {
+                    summarize: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let SegmentListing::Summary(stats) = listing else {
+            panic!("expected a summary, got entries");
+        };
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.total_bytes, sizes.iter().sum::<usize>() as u64);
+    }
+
+    /// A segment bigger than `multipart_threshold` must go through `store_multipart` and still
+    /// round-trip byte-for-byte.
+    #[tokio::test]
+    async fn s3_multipart_upload_roundtrip() {
+        let _ = tracing_subscriber::fmt::try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let (aws_config, _s3) = setup(&dir);
+
+        let s3_config = S3Config {
+            bucket: "testbucket".into(),
+            aws_config: aws_config.clone(),
+            cluster_id: "123456789".into(),
+            // Force the multipart path, and a part count spanning several concurrent uploads,
+            // without having to write a segment bigger than the 16 MiB default threshold.
+            multipart_threshold: 16 * 1024,
+            part_size: MIN_PART_SIZE,
+            max_concurrent_uploads: 4,
+            retry: RetryConfig::default(),
+            prefix: None,
+        };
+
+        let storage = S3Backend::from_sdk_config_with_io(
+            aws_config,
+            "testbucket".into(),
+            "123456789".into(),
+            StdIO(()),
+        )
+        .await
+        .unwrap();
+
+        // Spans several parts once `part_size` is clamped to `MIN_PART_SIZE`, including a final
+        // partial part.
+        let segment_len = (MIN_PART_SIZE * 3 + 1234) as usize;
+        let data: Vec<u8> = (0..segment_len).map(|i| (i % 251) as u8).collect();
+        let f_path = dir.path().join("fs-multipart-segment");
+        std::fs::write(&f_path, &data).unwrap();
+
+        let ns = NamespaceName::from_string("multipart-ns".into());
+
+        let mut builder = MapBuilder::memory();
+        builder.insert(0u64.to_be_bytes(), 0).unwrap();
+        let index = builder.into_inner().unwrap();
+        storage
+            .store(
+                &s3_config,
+                SegmentMeta {
+                    namespace: ns.clone(),
+                    segment_id: Uuid::new_v4(),
+                    start_frame_no: 0u64.into(),
+                    end_frame_no: 1u64.into(),
+                    created_at: Utc::now(),
+                },
+                std::fs::File::open(&f_path).unwrap(),
+                index,
+            )
+            .await
+            .unwrap();
+
+        let tmp = NamedTempFile::new().unwrap();
+        storage
+            .fetch_segment(&s3_config, ns.clone(), 0, tmp.path())
+            .await
+            .unwrap();
+
+        let fetched = std::fs::read(tmp.path()).unwrap();
+        assert_eq!(fetched, data);
+    }
+
+    #[test]
+    fn retry_backoff_caps_at_max_delay() {
+        let retry = RetryConfig {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            jitter: false,
+            mode: RetryMode::Standard,
+        };
+
+        assert_eq!(retry.backoff_delay(0), Duration::from_millis(100));
+        assert_eq!(retry.backoff_delay(1), Duration::from_millis(200));
+        assert_eq!(retry.backoff_delay(2), Duration::from_millis(400));
+        // Keeps doubling until it would exceed `max_delay`, then clamps.
+        assert_eq!(retry.backoff_delay(10), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn retry_backoff_adaptive_backs_off_harder() {
+        let standard = RetryConfig {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: false,
+            mode: RetryMode::Standard,
+        };
+        let adaptive = RetryConfig {
+            mode: RetryMode::Adaptive,
+            ..standard
+        };
+
+        assert!(adaptive.backoff_delay(1) > standard.backoff_delay(1));
+    }
+
+    fn test_config(prefix: Option<&str>) -> S3Config {
+        S3Config {
+            bucket: "testbucket".into(),
+            aws_config: SdkConfig::builder().build(),
+            cluster_id: "123456789".into(),
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            max_concurrent_uploads: DEFAULT_MAX_CONCURRENT_UPLOADS,
+            retry: RetryConfig::default(),
+            prefix: prefix.map(String::from),
+        }
+    }
+
+    #[test]
+    fn normalized_prefix_is_empty_when_unset() {
+        assert_eq!(test_config(None).normalized_prefix(), "");
+    }
+
+    #[test]
+    fn normalized_prefix_appends_a_trailing_slash() {
+        assert_eq!(
+            test_config(Some("tenant-a")).normalized_prefix(),
+            "tenant-a/"
+        );
+    }
+
+    #[test]
+    fn normalized_prefix_does_not_double_the_trailing_slash() {
+        assert_eq!(
+            test_config(Some("tenant-a/")).normalized_prefix(),
+            "tenant-a/"
+        );
+    }
+
+    #[test]
+    fn folder_key_embeds_the_normalized_prefix() {
+        let config = test_config(Some("tenant-a"));
+        let ns = NamespaceName::from_string("some-ns".into());
+        let prefix = config.normalized_prefix();
+        let folder_key = FolderKey {
+            prefix: &prefix,
+            cluster_id: &config.cluster_id,
+            namespace: &ns,
+        };
+        assert_eq!(folder_key.to_string(), "tenant-a/ns-123456789:some-ns-v2");
+    }
 }